@@ -0,0 +1,126 @@
+use crate::TaskSummary;
+use hdrhistogram::Histogram;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use tokio::sync::Mutex;
+
+/// One histogram per cluster and named status transition, e.g.
+/// `("my-cluster", "created->running")`. Keyed by cluster so percentiles
+/// from a slow/stuck cluster don't get diluted by a fast one when watching
+/// several at once.
+pub type Histograms = HashMap<(String, &'static str), Histogram<u64>>;
+
+/// Tracks, per cluster and transition, which task ARNs have already
+/// contributed a sample, so a task that stays listed across many polls is
+/// only recorded once per transition instead of once per poll.
+type Recorded = HashMap<(String, &'static str), HashSet<String>>;
+
+#[derive(Default)]
+pub struct HistogramState {
+    histograms: Histograms,
+    recorded: Recorded,
+}
+
+pub type SharedHistograms = Arc<Mutex<HistogramState>>;
+
+pub fn new_shared() -> SharedHistograms {
+    Arc::new(Mutex::new(HistogramState::default()))
+}
+
+fn record(
+    state: &mut HistogramState,
+    cluster_name: &str,
+    transition: &'static str,
+    task_arn: &str,
+    duration: chrono::Duration,
+) {
+    let key = (cluster_name.to_owned(), transition);
+    if !state
+        .recorded
+        .entry(key.clone())
+        .or_default()
+        .insert(task_arn.to_owned())
+    {
+        return;
+    }
+    let millis = duration.num_milliseconds();
+    if millis < 0 {
+        return;
+    }
+    let histogram = state
+        .histograms
+        .entry(key)
+        .or_insert_with(|| Histogram::new(3).expect("3 significant digits is a valid precision"));
+    let _ = histogram.record(millis as u64);
+}
+
+/// Feed every task's lifecycle timestamps into the relevant transition
+/// histograms for `cluster_name`. Tasks missing one side of a transition
+/// (e.g. still pending) simply don't contribute a sample for it yet; a task
+/// already recorded for a transition (by `task_arn`) on a previous poll is
+/// not recorded again.
+pub async fn record_transitions(
+    histograms: &SharedHistograms,
+    cluster_name: &str,
+    summary: &[TaskSummary],
+) {
+    let mut state = histograms.lock().await;
+    for task in summary {
+        if let (Some(created_at), Some(pull_started_at)) = (task.created_at, task.pull_started_at) {
+            record(
+                &mut state,
+                cluster_name,
+                "created->pull_started",
+                &task.task_arn,
+                pull_started_at - created_at,
+            );
+        }
+        if let (Some(pull_started_at), Some(pull_stopped_at)) =
+            (task.pull_started_at, task.pull_stopped_at)
+        {
+            record(
+                &mut state,
+                cluster_name,
+                "pull_started->pull_stopped",
+                &task.task_arn,
+                pull_stopped_at - pull_started_at,
+            );
+        }
+        if let (Some(created_at), Some(started_at)) = (task.created_at, task.started_at) {
+            record(
+                &mut state,
+                cluster_name,
+                "created->running",
+                &task.task_arn,
+                started_at - created_at,
+            );
+        }
+    }
+}
+
+/// Print p50/p90/p99/max for every cluster/transition pair with at least one
+/// sample, grouped by cluster so a multi-cluster watch doesn't mix a
+/// stuck cluster's latencies into a healthy one's.
+pub async fn print_percentiles(histograms: &SharedHistograms) {
+    let state = histograms.lock().await;
+    if state.histograms.is_empty() {
+        return;
+    }
+    println!("--- status-transition latency (milliseconds) ---");
+    let mut keys: Vec<&(String, &str)> = state.histograms.keys().collect();
+    keys.sort();
+    for (cluster_name, transition) in keys {
+        let histogram = &state.histograms[&(cluster_name.clone(), transition)];
+        println!(
+            "{:20} {:28} p50={:<8} p90={:<8} p99={:<8} max={}",
+            cluster_name,
+            transition,
+            histogram.value_at_quantile(0.50),
+            histogram.value_at_quantile(0.90),
+            histogram.value_at_quantile(0.99),
+            histogram.max(),
+        );
+    }
+}
@@ -1,14 +1,23 @@
+mod histogram;
+mod metrics;
+mod output;
+mod state;
+mod worker;
+
 use ansi_term::Style;
 use chrono::{NaiveDateTime, Utc};
 use clap::{AppSettings::ColoredHelp, Clap};
+use metrics::MetricsEmitter;
+use output::Format;
 use rusoto_core::{region::Region, RusotoError};
 use rusoto_ecs::{
-    Container, DescribeTasksError, DescribeTasksRequest, Ecs, EcsClient, ListTasksError,
-    ListTasksRequest,
+    Container, DescribeTasksError, DescribeTasksRequest, DescribeTasksResponse, Ecs, EcsClient,
+    ListTasksError, ListTasksRequest,
 };
+use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
-use std::{default::Default, env, str::FromStr};
-use tokio::time::delay_for;
+use std::{default::Default, env, path::PathBuf, str::FromStr, sync::Arc, time::Duration};
+use worker::{new_registry, Worker};
 
 /// Watch AWS Elastic Container Service (ECS) cluster changes
 #[derive(Clap, Clone, Debug)]
@@ -25,9 +34,10 @@ pub struct Args {
         short = "r"
     )]
     aws_region: String,
-    /// Cluster name to watch.
-    #[clap(env = "AWS_ECS_CLUSTER", long, short)]
-    cluster: String,
+    /// Cluster name to watch. May be given more than once to watch several
+    /// clusters concurrently.
+    #[clap(env = "AWS_ECS_CLUSTER", long, short, required = true)]
+    cluster: Vec<String>,
     /// Output the full task description response
     #[clap(long, short)]
     detail: bool,
@@ -35,6 +45,33 @@ pub struct Args {
     /// printing a new summary when anything in the summary changes.
     #[clap(long, short)]
     one_shot: bool,
+    /// How often, in seconds, to print a worker-status view summarizing
+    /// every watched cluster. Only meaningful with more than one `--cluster`.
+    /// Set to 0 to disable.
+    #[clap(long, default_value = "30")]
+    status_interval: u64,
+    /// Publish CloudWatch custom metrics for each watched cluster.
+    #[clap(long)]
+    emit_metrics: bool,
+    /// CloudWatch namespace to publish metrics under, when --emit-metrics is set.
+    #[clap(long, default_value = "ecs-watch")]
+    metrics_namespace: String,
+    /// Directory to persist each cluster's last-seen summary in. When set,
+    /// a restart prints a diff against what was seen before the restart.
+    #[clap(long)]
+    state_dir: Option<PathBuf>,
+    /// Output format for change events.
+    #[clap(long, default_value = "text", possible_values = &["text", "json"])]
+    format: Format,
+    /// Increase log verbosity. Overridden by RUST_LOG when it is set. Repeat
+    /// for more detail, e.g. -vv.
+    #[clap(long, short, parse(from_occurrences))]
+    verbose: u8,
+    /// Run a tokio-console server so `tokio-console` can attach and inspect
+    /// the polling tasks, their wakeups, and any stalls. Requires the binary
+    /// to have been built with the `tokio-console` feature.
+    #[clap(long)]
+    tokio_console: bool,
 }
 
 #[derive(Debug, Snafu)]
@@ -57,15 +94,55 @@ enum Error {
     ClusterNotFound { cluster_name: String },
 }
 
-#[derive(Clone, Debug, PartialEq, PartialOrd)]
-pub struct TaskSummary {
+#[derive(Clone, Debug, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub(crate) struct TaskSummary {
     date_time: NaiveDateTime,
     // desired_status: String,
     last_status: String,
     task_version: String,
     images: Vec<String>,
+    // Stable identity for this task instance, so a transition is only
+    // recorded into the latency histograms once, no matter how many polls
+    // the task stays listed for.
+    task_arn: String,
+    // Lifecycle timestamps, kept around for transition-latency histograms.
+    created_at: Option<NaiveDateTime>,
+    pull_started_at: Option<NaiveDateTime>,
+    pull_stopped_at: Option<NaiveDateTime>,
+    started_at: Option<NaiveDateTime>,
+    execution_stopped_at: Option<NaiveDateTime>,
+}
+
+/// Sets up the global `tracing` subscriber. `RUST_LOG` wins when set;
+/// otherwise `--verbose` selects a default level. `--tokio-console` swaps in
+/// the console-subscriber layer so `tokio-console` can attach, when the
+/// binary was built with the `tokio-console` feature.
+fn init_tracing(args: &Args) {
+    if args.tokio_console {
+        #[cfg(feature = "tokio-console")]
+        {
+            console_subscriber::init();
+            return;
+        }
+        #[cfg(not(feature = "tokio-console"))]
+        eprintln!(
+            "--tokio-console was given, but this binary was built without the \
+             \"tokio-console\" feature; falling back to normal logging"
+        );
+    }
+
+    let default_level = match args.verbose {
+        0 => "warn",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(default_level));
+    tracing_subscriber::fmt().with_env_filter(filter).init();
 }
 
+#[tracing::instrument(skip(ecs_client))]
 async fn tasks(ecs_client: &EcsClient, cluster_name: &str) -> Result<Vec<String>, Error> {
     let list_tasks_request = ListTasksRequest {
         cluster: Some(cluster_name.to_owned()),
@@ -75,6 +152,10 @@ async fn tasks(ecs_client: &EcsClient, cluster_name: &str) -> Result<Vec<String>
     let response = ecs_client
         .list_tasks(list_tasks_request)
         .await
+        .map_err(|source| {
+            tracing::error!(cluster_name, %source, "list_tasks failed");
+            source
+        })
         .context(TaskListLookup { cluster_name })?;
 
     match response.task_arns {
@@ -87,7 +168,7 @@ async fn tasks(ecs_client: &EcsClient, cluster_name: &str) -> Result<Vec<String>
 
 const DATE_TIME_FORMAT: &str = "%F %T";
 
-fn print_summary(summary: &[TaskSummary]) {
+pub(crate) fn print_summary(summary: &[TaskSummary]) {
     println!("{}", Utc::now().format(DATE_TIME_FORMAT));
     for (index, task) in summary.iter().enumerate() {
         let line = format!(
@@ -110,28 +191,13 @@ fn print_summary(summary: &[TaskSummary]) {
 }
 
 /// How long to sleep to get the next whole number of seconds.
-fn sleep_duration(seconds: u64) -> std::time::Duration {
+pub(crate) fn sleep_duration(seconds: u64) -> std::time::Duration {
     let now = Utc::now();
     // let now_seconds = now.timestamp();
     let now_millis = now.timestamp_subsec_millis() as u64;
     std::time::Duration::from_millis(1000 * seconds - now_millis)
 }
 
-async fn watch(ecs_client: &EcsClient, cluster_name: &str) -> Result<(), Error> {
-    let mut old_summary = task_summary(&ecs_client, cluster_name).await?;
-    print_summary(&old_summary);
-
-    loop {
-        delay_for(sleep_duration(2)).await;
-
-        let new_summary = task_summary(&ecs_client, cluster_name).await?;
-        if old_summary != new_summary {
-            print_summary(&new_summary);
-            old_summary = new_summary;
-        }
-    }
-}
-
 /// Timestamps from AWS are floating point seconds to millisecond precision.
 fn naive_date_time(timestamp: &f64) -> NaiveDateTime {
     let seconds = timestamp.to_owned() as i64;
@@ -139,6 +205,10 @@ fn naive_date_time(timestamp: &f64) -> NaiveDateTime {
     NaiveDateTime::from_timestamp(seconds, 1_000_000 * milliseconds)
 }
 
+fn optional_date_time(timestamp: &Option<f64>) -> Option<NaiveDateTime> {
+    timestamp.as_ref().map(naive_date_time)
+}
+
 fn newest_time(times: &[Option<f64>]) -> NaiveDateTime {
     let mut fs: Vec<f64> = times
         .iter()
@@ -186,22 +256,38 @@ fn images(containers: &Option<Vec<Container>>) -> Vec<String> {
         .collect()
 }
 
-async fn task_summary(
+#[tracing::instrument(skip(ecs_client))]
+async fn describe_tasks_response(
     ecs_client: &EcsClient,
     cluster_name: &str,
-) -> Result<Vec<TaskSummary>, Error> {
+) -> Result<DescribeTasksResponse, Error> {
     let describe_tasks_request = DescribeTasksRequest {
         cluster: Some(cluster_name.to_owned()),
         tasks: tasks(ecs_client, cluster_name).await?,
         ..Default::default()
     };
 
-    let result = ecs_client
+    ecs_client
         .describe_tasks(describe_tasks_request)
         .await
-        .context(TaskDescribe { cluster_name })?;
+        .map_err(|source| {
+            tracing::error!(cluster_name, %source, "describe_tasks failed");
+            source
+        })
+        .context(TaskDescribe { cluster_name })
+}
+
+/// Summarize a cluster's tasks, along with the raw describe-tasks response
+/// they were built from (used for `--detail` JSON output).
+#[tracing::instrument(skip(ecs_client))]
+pub(crate) async fn task_summary(
+    ecs_client: &EcsClient,
+    cluster_name: &str,
+) -> Result<(Vec<TaskSummary>, DescribeTasksResponse), Error> {
+    let result = describe_tasks_response(ecs_client, cluster_name).await?;
     let mut task_list: Vec<TaskSummary> = result
         .tasks
+        .clone()
         .unwrap_or_default()
         .iter()
         .map(|task| TaskSummary {
@@ -216,42 +302,113 @@ async fn task_summary(
             task_version: task_version(&task.task_definition_arn),
             last_status: task.last_status.clone().unwrap_or_default(),
             images: images(&task.containers),
+            task_arn: task.task_arn.clone().unwrap_or_default(),
+            created_at: optional_date_time(&task.created_at),
+            pull_started_at: optional_date_time(&task.pull_started_at),
+            pull_stopped_at: optional_date_time(&task.pull_stopped_at),
+            started_at: optional_date_time(&task.started_at),
+            execution_stopped_at: optional_date_time(&task.execution_stopped_at),
         })
         .collect();
     task_list.sort_by(|a, b| a.date_time.partial_cmp(&b.date_time).unwrap());
-    Ok(task_list)
+    Ok((task_list, result))
 }
 
-async fn detailed(ecs_client: &EcsClient, cluster_name: &str) -> Result<(), Error> {
-    let describe_tasks_request = DescribeTasksRequest {
-        cluster: Some(cluster_name.to_owned()),
-        tasks: tasks(ecs_client, cluster_name).await?,
-        ..Default::default()
-    };
-
-    let result = ecs_client
-        .describe_tasks(describe_tasks_request)
-        .await
-        .context(TaskDescribe { cluster_name })?;
-    println!("{:#?}", result);
+/// Print the raw describe-tasks payload for every cluster once, up front.
+/// `--format json` already carries `--detail` on every change event via
+/// `ChangeEvent.detail`, so this only runs for `--format text`, where the
+/// payload is too large to repeat on every change.
+async fn print_startup_detail(ecs_client: &EcsClient, clusters: &[String]) -> Result<(), Error> {
+    for cluster_name in clusters {
+        let response = describe_tasks_response(ecs_client, cluster_name).await?;
+        println!("--- {} ---", cluster_name);
+        println!("{:#?}", response);
+    }
     Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<(), exitfailure::ExitFailure> {
     let args = Args::parse();
+    init_tracing(&args);
 
     env::set_var("AWS_PROFILE", &args.aws_profile);
     let region = Region::from_str(&args.aws_region)?;
     let ecs_client = EcsClient::new(region.clone());
-    if args.detail {
-        detailed(&ecs_client, &args.cluster).await?
+
+    if args.detail && args.format == output::Format::Text {
+        print_startup_detail(&ecs_client, &args.cluster).await?;
+    }
+
+    let emitter = output::Emitter::new(args.format.clone());
+
+    let metrics_emitter = if args.emit_metrics {
+        Some(Arc::new(MetricsEmitter::new(
+            region,
+            args.metrics_namespace.clone(),
+        )))
+    } else {
+        None
     };
+
     if args.one_shot {
-        let summary = task_summary(&ecs_client, &args.cluster).await?;
-        print_summary(&summary);
-    } else {
-        watch(&ecs_client, &args.cluster).await?;
+        let histograms = histogram::new_shared();
+        for cluster_name in &args.cluster {
+            let (summary, response) = task_summary(&ecs_client, cluster_name).await?;
+
+            if let Some(state_dir) = &args.state_dir {
+                if let Some(previous) = state::load(state_dir, cluster_name) {
+                    state::print_diff(&previous, &summary);
+                }
+                if let Err(error) = state::save(state_dir, cluster_name, &summary) {
+                    tracing::error!(cluster_name, %error, "failed to persist state");
+                }
+            }
+
+            emitter.emit(
+                cluster_name,
+                &summary,
+                if args.detail { Some(&response) } else { None },
+            );
+            histogram::record_transitions(&histograms, cluster_name, &summary).await;
+            if let Some(metrics_emitter) = &metrics_emitter {
+                metrics_emitter.emit(cluster_name, &summary).await;
+            }
+        }
+        histogram::print_percentiles(&histograms).await;
+        return Ok(());
+    }
+
+    let registry = new_registry();
+    let histograms = histogram::new_shared();
+    let mut handles = Vec::new();
+    for cluster_name in &args.cluster {
+        let worker = Worker::new(
+            cluster_name.clone(),
+            registry.clone(),
+            metrics_emitter.clone(),
+            args.state_dir.clone(),
+            histograms.clone(),
+            emitter.clone(),
+            args.detail,
+        );
+        handles.push(tokio::spawn(worker.run(ecs_client.clone())));
+    }
+
+    if args.cluster.len() > 1 && args.status_interval > 0 {
+        let status_registry = registry.clone();
+        let interval = Duration::from_secs(args.status_interval);
+        handles.push(tokio::spawn(worker::run_status_printer(
+            status_registry,
+            interval,
+        )));
+    }
+
+    tokio::select! {
+        _ = futures::future::join_all(handles) => {}
+        _ = tokio::signal::ctrl_c() => {
+            histogram::print_percentiles(&histograms).await;
+        }
     }
     Ok(())
 }
@@ -0,0 +1,96 @@
+use crate::TaskSummary;
+use chrono::Utc;
+use rusoto_cloudwatch::{CloudWatch, CloudWatchClient, Dimension, MetricDatum, PutMetricDataInput};
+use rusoto_core::Region;
+use std::collections::{HashMap, HashSet};
+
+/// CloudWatch's `PutMetricData` accepts at most this many datums per request.
+const MAX_METRICS_PER_REQUEST: usize = 20;
+
+/// Publishes CloudWatch custom metrics for a cluster's task summaries, so
+/// alarms can be built on stuck deployments instead of watching a terminal.
+pub struct MetricsEmitter {
+    client: CloudWatchClient,
+    namespace: String,
+    region_name: String,
+}
+
+impl MetricsEmitter {
+    pub fn new(region: Region, namespace: String) -> Self {
+        let region_name = region.name().to_owned();
+        MetricsEmitter {
+            client: CloudWatchClient::new(region),
+            namespace,
+            region_name,
+        }
+    }
+
+    /// Compute and publish metrics for a freshly-changed summary: task
+    /// counts by `last_status`, the number of distinct `task_version`
+    /// values currently deployed, and a change-event counter.
+    pub async fn emit(&self, cluster_name: &str, summary: &[TaskSummary]) {
+        let cluster_dimension = Dimension {
+            name: "ClusterName".to_owned(),
+            value: cluster_name.to_owned(),
+        };
+        let region_dimension = Dimension {
+            name: "Region".to_owned(),
+            value: self.region_name.clone(),
+        };
+        let timestamp = Utc::now().to_rfc3339();
+
+        let mut counts_by_status: HashMap<String, f64> = HashMap::new();
+        for task in summary {
+            *counts_by_status.entry(task.last_status.clone()).or_insert(0.0) += 1.0;
+        }
+
+        let mut datums: Vec<MetricDatum> = counts_by_status
+            .into_iter()
+            .map(|(last_status, count)| MetricDatum {
+                metric_name: "TasksByStatus".to_owned(),
+                dimensions: Some(vec![
+                    cluster_dimension.clone(),
+                    region_dimension.clone(),
+                    Dimension {
+                        name: "LastStatus".to_owned(),
+                        value: last_status,
+                    },
+                ]),
+                timestamp: Some(timestamp.clone()),
+                value: Some(count),
+                ..Default::default()
+            })
+            .collect();
+
+        let distinct_task_versions = summary
+            .iter()
+            .map(|task| &task.task_version)
+            .collect::<HashSet<_>>()
+            .len() as f64;
+        datums.push(MetricDatum {
+            metric_name: "DeployedTaskVersions".to_owned(),
+            dimensions: Some(vec![cluster_dimension.clone(), region_dimension.clone()]),
+            timestamp: Some(timestamp.clone()),
+            value: Some(distinct_task_versions),
+            ..Default::default()
+        });
+
+        datums.push(MetricDatum {
+            metric_name: "ChangeEvents".to_owned(),
+            dimensions: Some(vec![cluster_dimension, region_dimension]),
+            timestamp: Some(timestamp),
+            value: Some(1.0),
+            ..Default::default()
+        });
+
+        for chunk in datums.chunks(MAX_METRICS_PER_REQUEST) {
+            let request = PutMetricDataInput {
+                namespace: self.namespace.clone(),
+                metric_data: chunk.to_vec(),
+            };
+            if let Err(error) = self.client.put_metric_data(request).await {
+                tracing::error!(cluster_name, %error, "failed to publish CloudWatch metrics");
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use crate::TaskSummary;
+use chrono::{DateTime, Utc};
+use rusoto_ecs::DescribeTasksResponse;
+use serde::Serialize;
+use std::{io::Write, str::FromStr};
+
+/// Output format for change events: human-readable text, or one JSON
+/// object per line for piping into `jq`, a log shipper, or a dashboard.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            other => Err(format!("unknown output format \"{}\"", other)),
+        }
+    }
+}
+
+/// Schema of a single `--format json` line: the capture timestamp, cluster
+/// name, and the full summary, plus (with `--detail`) the raw
+/// describe-tasks response that produced it.
+#[derive(Serialize)]
+struct ChangeEvent<'a> {
+    captured_at: DateTime<Utc>,
+    cluster_name: &'a str,
+    tasks: &'a [TaskSummary],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<&'a DescribeTasksResponse>,
+}
+
+/// Routes a change event to the chosen output format. Each cluster's
+/// `Worker` poll loop and the `--one-shot` path in `main` both emit through
+/// this rather than printing directly.
+#[derive(Clone)]
+pub struct Emitter {
+    format: Format,
+}
+
+impl Emitter {
+    pub fn new(format: Format) -> Self {
+        Emitter { format }
+    }
+
+    pub fn emit(
+        &self,
+        cluster_name: &str,
+        tasks: &[TaskSummary],
+        detail: Option<&DescribeTasksResponse>,
+    ) {
+        match self.format {
+            // Text is for a human watching the terminal: the summary line is
+            // the per-event output. The full describe-tasks payload is too
+            // large to repeat on every change, so it's only ever printed
+            // once at startup (see `print_startup_detail` in main.rs).
+            Format::Text => crate::print_summary(tasks),
+            Format::Json => {
+                let event = ChangeEvent {
+                    captured_at: Utc::now(),
+                    cluster_name,
+                    tasks,
+                    detail,
+                };
+                println!(
+                    "{}",
+                    serde_json::to_string(&event).expect("ChangeEvent always serializes")
+                );
+                let _ = std::io::stdout().flush();
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use crate::TaskSummary;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// On-disk schema version, bumped whenever `PersistedState`'s shape changes
+/// in a way that isn't backward compatible.
+const STATE_SCHEMA_VERSION: u32 = 1;
+
+/// The latest summary for a cluster, as written to and read from a state
+/// file under `--state-dir`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PersistedState {
+    version: u32,
+    captured_at: DateTime<Utc>,
+    summary: Vec<TaskSummary>,
+}
+
+/// Cluster names are often full ARNs (`arn:aws:ecs:...:cluster/name`), which
+/// contain `/` and `:` and would otherwise turn a single path component into
+/// several, or escape `state_dir` entirely. Replace anything but
+/// alphanumerics, `-`, and `_` so the result is always a single safe
+/// filename.
+fn sanitize_for_filename(cluster_name: &str) -> String {
+    cluster_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn state_file_path(state_dir: &Path, cluster_name: &str) -> PathBuf {
+    state_dir.join(format!("{}.json", sanitize_for_filename(cluster_name)))
+}
+
+/// Atomically persist `summary` for `cluster_name`: write to a temporary
+/// file in `state_dir`, then rename it over the target so a reader never
+/// observes a partial write.
+pub fn save(state_dir: &Path, cluster_name: &str, summary: &[TaskSummary]) -> io::Result<()> {
+    fs::create_dir_all(state_dir)?;
+    let path = state_file_path(state_dir, cluster_name);
+    let tmp_path = path.with_extension("json.tmp");
+    let state = PersistedState {
+        version: STATE_SCHEMA_VERSION,
+        captured_at: Utc::now(),
+        summary: summary.to_vec(),
+    };
+    fs::write(&tmp_path, serde_json::to_vec_pretty(&state)?)?;
+    fs::rename(tmp_path, path)
+}
+
+/// Load the previously persisted state for `cluster_name`, if any. Returns
+/// `None` on a missing, unreadable, or unrecognized-version file rather than
+/// failing the whole run — losing the diff-on-restart is not worth refusing
+/// to start.
+pub fn load(state_dir: &Path, cluster_name: &str) -> Option<PersistedState> {
+    let bytes = fs::read(state_file_path(state_dir, cluster_name)).ok()?;
+    let state: PersistedState = serde_json::from_slice(&bytes).ok()?;
+    if state.version != STATE_SCHEMA_VERSION {
+        return None;
+    }
+    Some(state)
+}
+
+/// Print what moved between a persisted summary and a freshly polled one:
+/// newly appeared task versions, statuses that changed, and images that
+/// rolled. First prints when the persisted state was captured and how many
+/// whole hours ago that was, whatever its age.
+pub fn print_diff(previous: &PersistedState, current: &[TaskSummary]) {
+    let staleness = Utc::now().signed_duration_since(previous.captured_at);
+    println!(
+        "--- resuming from state captured {} ({} hours ago) ---",
+        previous.captured_at.format("%F %T"),
+        staleness.num_hours()
+    );
+
+    let previous_versions: HashSet<&str> = previous
+        .summary
+        .iter()
+        .map(|task| task.task_version.as_str())
+        .collect();
+    let current_versions: HashSet<&str> = current
+        .iter()
+        .map(|task| task.task_version.as_str())
+        .collect();
+
+    for version in &current_versions - &previous_versions {
+        println!("+ new task version: {}", version);
+    }
+
+    // Match on task_arn, not task_version: a service with desired_count > 1
+    // runs several tasks sharing one version, so version alone can't tell
+    // which previous task a current one corresponds to.
+    let previous_by_arn: std::collections::HashMap<&str, &TaskSummary> = previous
+        .summary
+        .iter()
+        .map(|task| (task.task_arn.as_str(), task))
+        .collect();
+    for task in current {
+        if let Some(previous_task) = previous_by_arn.get(task.task_arn.as_str()) {
+            if previous_task.last_status != task.last_status {
+                println!(
+                    "~ {} ({}) status {} -> {}",
+                    task.task_version, task.task_arn, previous_task.last_status, task.last_status
+                );
+            }
+            if previous_task.images != task.images {
+                println!(
+                    "~ {} ({}) images {:?} -> {:?}",
+                    task.task_version, task.task_arn, previous_task.images, task.images
+                );
+            }
+        }
+    }
+}
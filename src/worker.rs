@@ -0,0 +1,183 @@
+use crate::{
+    histogram::SharedHistograms, metrics::MetricsEmitter, output::Emitter, sleep_duration, state,
+    task_summary, TaskSummary,
+};
+use rusoto_ecs::EcsClient;
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+use tokio::{sync::Mutex, time::delay_for};
+use tracing::Instrument;
+
+/// Give up on a cluster after this many back-to-back failed polls.
+const MAX_CONSECUTIVE_ERRORS: u32 = 8;
+
+/// Cap exponential backoff between retries at one minute.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Last-observed state of a single cluster's polling worker.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WorkerState {
+    /// A change was just printed for this cluster.
+    Active,
+    /// Polled successfully, but nothing changed.
+    Idle,
+    /// The last poll failed; the worker is backing off before retrying.
+    Errored(String),
+    /// Too many consecutive failures; this worker has stopped retrying.
+    Dead,
+}
+
+/// Shared map from cluster name to its worker's current state, updated by
+/// each worker and read by the periodic status printer.
+pub type Registry = Arc<Mutex<HashMap<String, WorkerState>>>;
+
+pub fn new_registry() -> Registry {
+    Arc::new(Mutex::new(HashMap::new()))
+}
+
+/// Runs the `task_summary` poll loop for a single cluster, reporting its
+/// state into a shared `Registry` instead of propagating errors to the
+/// caller. A failed poll is retried with exponential backoff; a cluster
+/// that fails too many times in a row is marked `Dead` and gives up.
+pub struct Worker {
+    cluster_name: String,
+    registry: Registry,
+    metrics_emitter: Option<Arc<MetricsEmitter>>,
+    state_dir: Option<PathBuf>,
+    histograms: SharedHistograms,
+    emitter: Emitter,
+    include_detail: bool,
+}
+
+impl Worker {
+    pub fn new(
+        cluster_name: String,
+        registry: Registry,
+        metrics_emitter: Option<Arc<MetricsEmitter>>,
+        state_dir: Option<PathBuf>,
+        histograms: SharedHistograms,
+        emitter: Emitter,
+        include_detail: bool,
+    ) -> Self {
+        Worker {
+            cluster_name,
+            registry,
+            metrics_emitter,
+            state_dir,
+            histograms,
+            emitter,
+            include_detail,
+        }
+    }
+
+    async fn set_state(&self, state: WorkerState) {
+        self.registry
+            .lock()
+            .await
+            .insert(self.cluster_name.clone(), state);
+    }
+
+    pub async fn run(self, ecs_client: EcsClient) {
+        let mut old_summary: Option<Vec<TaskSummary>> = None;
+        let mut backoff = Duration::from_secs(1);
+        let mut consecutive_errors = 0u32;
+        let mut first_poll = true;
+        let mut iteration = 0u64;
+
+        loop {
+            iteration += 1;
+            let poll_span = tracing::info_span!("watch", cluster = %self.cluster_name, iteration);
+
+            match task_summary(&ecs_client, &self.cluster_name)
+                .instrument(poll_span)
+                .await
+            {
+                Ok((new_summary, response)) => {
+                    backoff = Duration::from_secs(1);
+                    consecutive_errors = 0;
+                    crate::histogram::record_transitions(
+                        &self.histograms,
+                        &self.cluster_name,
+                        &new_summary,
+                    )
+                    .await;
+                    if first_poll {
+                        first_poll = false;
+                        if let Some(state_dir) = &self.state_dir {
+                            if let Some(previous) = state::load(state_dir, &self.cluster_name) {
+                                state::print_diff(&previous, &new_summary);
+                            }
+                        }
+                    }
+                    if old_summary.as_ref() != Some(&new_summary) {
+                        self.emitter.emit(
+                            &self.cluster_name,
+                            &new_summary,
+                            if self.include_detail {
+                                Some(&response)
+                            } else {
+                                None
+                            },
+                        );
+                        if let Some(metrics_emitter) = &self.metrics_emitter {
+                            metrics_emitter.emit(&self.cluster_name, &new_summary).await;
+                        }
+                        if let Some(state_dir) = &self.state_dir {
+                            if let Err(error) = state::save(state_dir, &self.cluster_name, &new_summary) {
+                                tracing::error!(
+                                    cluster = %self.cluster_name, %error, "failed to persist state"
+                                );
+                            }
+                        }
+                        old_summary = Some(new_summary);
+                        self.set_state(WorkerState::Active).await;
+                    } else {
+                        self.set_state(WorkerState::Idle).await;
+                    }
+                    delay_for(sleep_duration(2)).await;
+                }
+                Err(error) => {
+                    consecutive_errors += 1;
+                    tracing::error!(
+                        cluster = %self.cluster_name, %error, consecutive_errors, "poll failed"
+                    );
+                    self.set_state(WorkerState::Errored(error.to_string())).await;
+                    if consecutive_errors >= MAX_CONSECUTIVE_ERRORS {
+                        tracing::error!(cluster = %self.cluster_name, "giving up after too many consecutive failures");
+                        self.set_state(WorkerState::Dead).await;
+                        return;
+                    }
+                    delay_for(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+    }
+}
+
+/// Print a one-line-per-cluster view of the registry, so a user watching
+/// many clusters can see at a glance which ones are stalled or erroring.
+pub async fn print_registry(registry: &Registry) {
+    let states = registry.lock().await;
+    println!("--- worker status ---");
+    let mut names: Vec<&String> = states.keys().collect();
+    names.sort();
+    for name in names {
+        let state = &states[name];
+        let description = match state {
+            WorkerState::Active => "active".to_owned(),
+            WorkerState::Idle => "idle".to_owned(),
+            WorkerState::Errored(message) => format!("errored: {}", message),
+            WorkerState::Dead => "dead".to_owned(),
+        };
+        println!("{:30} {}", name, description);
+    }
+}
+
+/// Periodically prints the registry until the process exits. Intended to be
+/// run as its own `tokio::spawn`ed task alongside the per-cluster workers.
+pub async fn run_status_printer(registry: Registry, interval: Duration) {
+    loop {
+        delay_for(interval).await;
+        print_registry(&registry).await;
+    }
+}